@@ -1,14 +1,17 @@
 mod error;
+mod stretch;
 mod swath;
 mod transform;
 mod types;
 mod validation;
+mod vrt;
+mod warp;
 
 use std::path::Path;
 
 use gdal::raster::dataset::Buffer;
 use gdal::raster::types::GdalType;
-use gdal::raster::{Dataset, Driver, RasterBand};
+use gdal::raster::{ColorInterpretation, Dataset, Driver, RasterBand};
 
 use gdal_sys::GDALDataType;
 
@@ -36,56 +39,271 @@ fn split_path_band<'a>(input: &'a str) -> (&'a str, isize) {
     (path, band)
 }
 
-fn ply_bands<T: Copy + GdalType + GdalFrom<f64>>(
+// True when `v` should be treated as NoData. Plain equality isn't enough for
+// Float32/Float64 inputs using NaN as their NoData sentinel, since NaN != NaN
+// under IEEE 754 — such values are matched by checking both sides are NaN
+// instead.
+fn is_nodata<T: Copy + PartialEq + Into<f64>>(v: T, nodata_t: T, nodata: f64) -> bool {
+    v == nodata_t || (nodata.is_nan() && v.into().is_nan())
+}
+
+// Mark pixels as transparent (0) in `alpha` wherever the extracted band value
+// matches its source NoData value. Pixels are left untouched otherwise, so
+// that calling this once per channel yields the union of each channel's
+// invalid pixels. `y_offset`/`width` locate the (possibly partial) strip
+// `buf` covers within the full `alpha` buffer.
+fn mask_nodata<T: Copy + GdalFrom<f64> + PartialEq + Into<f64>>(
+    alpha: &mut [u8],
+    width: usize,
+    y_offset: isize,
+    buf: &Buffer<T>,
+    nodata: Option<f64>,
+) {
+    if let Some(nd) = nodata {
+        let nd_t = T::gdal_from(nd);
+        let start = y_offset as usize * width;
+        for (a, v) in alpha[start..start + buf.data.len()].iter_mut().zip(buf.data.iter()) {
+            if is_nodata(*v, nd_t, nd) {
+                *a = 0;
+            }
+        }
+    }
+}
+
+fn ply_one_band<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
+    ds: &Dataset,
+    dest_band: isize,
+    sw: &Swath,
+    band: &RasterBand,
+    block_lines: usize,
+    width: usize,
+    alpha: &mut [u8],
+    resampling: &warp::Resampling,
+) -> Result<(), Error> {
+    let nodata = band.no_data_value();
+    let typed = TypedRasterBand::from_rasterband(band).map_err(|e| Error::from(e))?;
+
+    swath::extract_auto(sw, band, &typed, block_lines, resampling, |y_offset, buf| {
+        mask_nodata(alpha, width, y_offset, &buf, nodata);
+        let window = (buf.size.0, buf.size.1);
+        ds.write_raster(dest_band, (0, y_offset), window, &buf)
+            .map_err(|e| Error::from(e))
+    })?;
+
+    if let Some(nd) = nodata {
+        ds.rasterband(dest_band)?.set_no_data_value(nd)?;
+    }
+
+    Ok(())
+}
+
+fn ply_one_band_stretched<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
+    ds: &Dataset,
+    dest_band: isize,
+    sw: &Swath,
+    band: &RasterBand,
+    block_lines: usize,
+    width: usize,
+    alpha: &mut [u8],
+    resampling: &warp::Resampling,
+    stretch: &stretch::Stretch,
+) -> Result<(), Error> {
+    let nodata = band.no_data_value();
+    let nodata_t = nodata.map(T::gdal_from);
+    let typed = TypedRasterBand::from_rasterband(band).map_err(|e| Error::from(e))?;
+
+    let range = stretch::compute_range(sw, band, &typed, block_lines, nodata, resampling, stretch)?;
+
+    swath::extract_auto(sw, band, &typed, block_lines, resampling, |y_offset, buf| {
+        mask_nodata(alpha, width, y_offset, &buf, nodata);
+        let scaled: Vec<u8> = buf
+            .data
+            .iter()
+            .map(|&v| match (nodata, nodata_t) {
+                (Some(nd), Some(nd_t)) if is_nodata(v, nd_t, nd) => 0,
+                _ => range.to_u8(v.into()),
+            })
+            .collect();
+        let out = Buffer {
+            size: buf.size,
+            data: scaled,
+        };
+        ds.write_raster(dest_band, (0, y_offset), out.size, &out)
+            .map_err(|e| Error::from(e))
+    })?;
+
+    Ok(())
+}
+
+fn ply_bands_stretched<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
     output: &OutputOptions,
     sw: Swath,
     projection: String,
     band1: &RasterBand,
     band2: &RasterBand,
     band3: &RasterBand,
+    block_lines: usize,
+    resampling: &warp::Resampling,
+    stretch: &stretch::Stretch,
 ) -> Result<Dataset, Error> {
     let driver = Driver::get(&output.format).unwrap();
 
     let ds = driver
-        .create_with_band_type::<T>(&output.filename, sw.nx.abs(), sw.ny.abs(), 3)
+        .create_with_band_type::<u8>(&output.filename, sw.nx.abs(), sw.ny.abs(), 4)
         .expect("failed to create output dataset");
 
     ds.set_projection(&projection)?;
     ds.set_geo_transform(&sw.gt)?;
 
-    let buf: Buffer<T> = TypedRasterBand::from_rasterband(band1)
-        .map_err(|e| Error::from(e))
-        .and_then(|b| swath::extract(&sw, &b).map_err(|e| Error::from(e)))?;
-    ds.write_raster(
-        1,
-        (0, 0),
-        (sw.nx.abs() as usize, sw.ny.abs() as usize),
-        &buf,
-    )?;
+    let size = (sw.nx.abs() as usize, sw.ny.abs() as usize);
+    let mut alpha: Vec<u8> = vec![255u8; size.0 * size.1];
 
-    let buf: Buffer<T> = TypedRasterBand::from_rasterband(band2)
-        .map_err(|e| Error::from(e))
-        .and_then(|b| swath::extract(&sw, &b).map_err(|e| Error::from(e)))?;
-    ds.write_raster(
-        2,
-        (0, 0),
-        (sw.nx.abs() as usize, sw.ny.abs() as usize),
-        &buf,
+    ply_one_band_stretched::<T>(
+        &ds, 1, &sw, band1, block_lines, size.0, &mut alpha, resampling, stretch,
     )?;
-
-    let buf: Buffer<T> = TypedRasterBand::from_rasterband(band3)
-        .map_err(|e| Error::from(e))
-        .and_then(|b| swath::extract(&sw, &b).map_err(|e| Error::from(e)))?;
-    ds.write_raster(
-        3,
-        (0, 0),
-        (sw.nx.abs() as usize, sw.ny.abs() as usize),
-        &buf,
+    ply_one_band_stretched::<T>(
+        &ds, 2, &sw, band2, block_lines, size.0, &mut alpha, resampling, stretch,
     )?;
+    ply_one_band_stretched::<T>(
+        &ds, 3, &sw, band3, block_lines, size.0, &mut alpha, resampling, stretch,
+    )?;
+
+    let alpha_band = ds.rasterband(4)?;
+    alpha_band.set_color_interpretation(ColorInterpretation::AlphaBand)?;
+    let alpha_buf = Buffer {
+        size,
+        data: alpha,
+    };
+    ds.write_raster(4, (0, 0), size, &alpha_buf)?;
+
+    Ok(ds)
+}
+
+fn ply_bands<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
+    output: &OutputOptions,
+    sw: Swath,
+    projection: String,
+    band1: &RasterBand,
+    band2: &RasterBand,
+    band3: &RasterBand,
+    block_lines: usize,
+    resampling: &warp::Resampling,
+) -> Result<Dataset, Error> {
+    let driver = Driver::get(&output.format).unwrap();
+
+    let ds = driver
+        .create_with_band_type::<T>(&output.filename, sw.nx.abs(), sw.ny.abs(), 4)
+        .expect("failed to create output dataset");
+
+    ds.set_projection(&projection)?;
+    ds.set_geo_transform(&sw.gt)?;
+
+    let size = (sw.nx.abs() as usize, sw.ny.abs() as usize);
+    let mut alpha: Vec<u8> = vec![255u8; size.0 * size.1];
+
+    ply_one_band::<T>(&ds, 1, &sw, band1, block_lines, size.0, &mut alpha, resampling)?;
+    ply_one_band::<T>(&ds, 2, &sw, band2, block_lines, size.0, &mut alpha, resampling)?;
+    ply_one_band::<T>(&ds, 3, &sw, band3, block_lines, size.0, &mut alpha, resampling)?;
+
+    let alpha_band = ds.rasterband(4)?;
+    alpha_band.set_color_interpretation(ColorInterpretation::AlphaBand)?;
+    let alpha_buf = Buffer {
+        size,
+        data: alpha,
+    };
+    ds.write_raster(4, (0, 0), size, &alpha_buf)?;
 
     Ok(ds)
 }
 
+// Dispatch to the plain or contrast-stretched compositing path once the
+// final (possibly warped) source bands are known.
+fn run_ply<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
+    output: &OutputOptions,
+    sw: Swath,
+    projection: String,
+    red_band: &RasterBand,
+    green_band: &RasterBand,
+    blue_band: &RasterBand,
+    block_lines: usize,
+    resampling: &warp::Resampling,
+    stretch: &stretch::Stretch,
+) -> Result<Dataset, Error> {
+    match stretch {
+        stretch::Stretch::None => ply_bands::<T>(
+            output,
+            sw,
+            projection,
+            red_band,
+            green_band,
+            blue_band,
+            block_lines,
+            resampling,
+        ),
+        other => ply_bands_stretched::<T>(
+            output,
+            sw,
+            projection,
+            red_band,
+            green_band,
+            blue_band,
+            block_lines,
+            resampling,
+            other,
+        ),
+    }
+}
+
+// Run the compositing pipeline for one pixel type. When `needs_warp` says the
+// inputs didn't already share a projection, each source band is first warped
+// onto `sw`'s grid/SRS; `resampling` otherwise also selects how `extract_auto`
+// resamples a source whose resolution doesn't match `sw`'s.
+fn process<T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>>(
+    output: &OutputOptions,
+    sw: Swath,
+    projection: String,
+    red_band: &RasterBand,
+    green_band: &RasterBand,
+    blue_band: &RasterBand,
+    block_lines: usize,
+    needs_warp: bool,
+    resampling: &warp::Resampling,
+    stretch: &stretch::Stretch,
+) -> Result<Dataset, Error> {
+    if !needs_warp {
+        return run_ply::<T>(
+            output,
+            sw,
+            projection,
+            red_band,
+            green_band,
+            blue_band,
+            block_lines,
+            resampling,
+            stretch,
+        );
+    }
+
+    let warped_red = warp::warp_to_swath::<T>(red_band, &sw, resampling)?;
+    let warped_green = warp::warp_to_swath::<T>(green_band, &sw, resampling)?;
+    let warped_blue = warp::warp_to_swath::<T>(blue_band, &sw, resampling)?;
+    let red_band = warped_red.rasterband(1)?;
+    let green_band = warped_green.rasterband(1)?;
+    let blue_band = warped_blue.rasterband(1)?;
+
+    run_ply::<T>(
+        output,
+        sw,
+        projection,
+        &red_band,
+        &green_band,
+        &blue_band,
+        block_lines,
+        resampling,
+        stretch,
+    )
+}
+
 fn main() {
     let cli = App::new("plyband")
         .version("0.1.0")
@@ -132,6 +350,41 @@ fn main() {
                 .value_name("FORMAT")
                 .help("Output format driver"),
         )
+        .arg(
+            Arg::with_name("block_lines")
+                .long("block-lines")
+                .value_name("LINES")
+                .help("Rows to process per strip (defaults to the red band's native block height)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("target_srs")
+                .long("target-srs")
+                .value_name("WKT|EPSG:CODE")
+                .help("Reproject mismatched inputs into this SRS instead of rejecting them")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("resampling")
+                .long("resampling")
+                .value_name("METHOD")
+                .help("Resampling method used by --target-srs and --resolution: nearest, bilinear, or cubic")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("stretch")
+                .long("stretch")
+                .value_name("MODE")
+                .help("Per-band contrast stretch to 8-bit output: none, minmax, or pN-M (percentiles)")
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("resolution")
+                .long("resolution")
+                .value_name("source|finest|coarsest|VALUE")
+                .help("Combine inputs at different resolutions onto this common pixel size")
+                .required(false),
+        )
         .get_matches();
 
     let (red_input, rb) = split_path_band(cli.value_of("red").unwrap());
@@ -148,89 +401,221 @@ fn main() {
     let datasets = &[&red_ds, &green_ds, &blue_ds];
 
     // Input validation
-    let checks = validation::have_same_projection(datasets)
-        .and_then(|_| validation::have_compatible_geotransforms(datasets));
+    let proj_check = validation::have_same_projection(datasets);
+    let geo_check = validation::have_compatible_geotransforms(datasets);
 
-    match checks {
-        Ok(()) => (),
-        Err(msg) => {
-            eprintln!("Validation failed:\n{}", msg);
+    let red_band = red_ds.rasterband(rb).unwrap();
+    let green_band = green_ds.rasterband(gb).unwrap();
+    let blue_band = blue_ds.rasterband(bb).unwrap();
+
+    let target_srs_arg = cli.value_of("target_srs");
+    let resolution_arg = cli.value_of("resolution");
+    let resampling = match cli.value_of("resampling").map(warp::Resampling::parse) {
+        None => warp::Resampling::Bilinear,
+        Some(Ok(method)) => method,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
             std::process::exit(1);
         }
     };
 
-    let red_band = red_ds.rasterband(rb).unwrap();
-    let green_band = green_ds.rasterband(gb).unwrap();
-    let blue_band = blue_ds.rasterband(bb).unwrap();
+    // `needs_warp` means sources didn't share a projection, so `process` must
+    // reproject each onto `sw`'s grid before compositing. `needs_resample`
+    // means sources shared a projection but not a pixel size, so `sw`'s grid
+    // was chosen independently via `--resolution`; `extract_auto` resamples
+    // each source into it as it's read, rather than materializing a warped
+    // copy up front.
+    let (sw, needs_warp, needs_resample) = if proj_check.is_ok() && geo_check.is_ok() {
+        let sw = swath::intersection(&[&red_band, &green_band, &blue_band])
+            .expect("Failed to compute intersection between bands");
+        (sw, false, false)
+    } else if let Err(msg) = proj_check {
+        match target_srs_arg {
+            Some(target) => {
+                let target_srs = warp::parse_target_srs(target).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+                let sw =
+                    swath::intersection_in(&[&red_band, &green_band, &blue_band], &target_srs)
+                        .unwrap_or_else(|e| {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        });
+                (sw, true, false)
+            }
+            None => {
+                eprintln!("Validation failed:\n{}", msg);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        // Projections agree, so the remaining disagreement must be the
+        // geotransforms (typically a pixel-spacing mismatch).
+        match resolution_arg {
+            Some(res) => {
+                let resolution = swath::ResolutionChoice::parse(res).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+                let sw = swath::intersection_at_resolution(
+                    &[&red_band, &green_band, &blue_band],
+                    &resolution,
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
+                (sw, false, true)
+            }
+            None => {
+                eprintln!("Validation failed:\n{}", geo_check.unwrap_err());
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let proj = sw.proj.clone();
 
-    let proj = red_ds.projection();
+    let block_lines = cli
+        .value_of("block_lines")
+        .map(|s| s.parse::<usize>().expect("--block-lines must be an integer"))
+        .unwrap_or_else(|| swath::native_block_height(&red_band));
 
-    let sw = swath::intersection(&[&red_band, &green_band, &blue_band])
-        .expect("Failed to compute intersection between bands");
+    let stretch = match cli.value_of("stretch").map(stretch::Stretch::parse) {
+        None => stretch::Stretch::None,
+        Some(Ok(mode)) => mode,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let pixel_type = red_band.band_type();
+
+    if output_format.eq_ignore_ascii_case("VRT") {
+        if needs_warp {
+            eprintln!("--output-format VRT cannot be combined with --target-srs");
+            std::process::exit(1);
+        }
+        if needs_resample {
+            eprintln!("--output-format VRT cannot be combined with --resolution");
+            std::process::exit(1);
+        }
+        if let stretch::Stretch::Percentile(..) | stretch::Stretch::MinMax = stretch {
+            eprintln!("--output-format VRT cannot be combined with --stretch");
+            std::process::exit(1);
+        }
+
+        let result = vrt::write(
+            output,
+            &sw,
+            pixel_type,
+            [
+                (red_input, rb, &red_band),
+                (green_input, gb, &green_band),
+                (blue_input, bb, &blue_band),
+            ],
+        );
+
+        std::process::exit(match result {
+            Ok(()) => 0,
+            Err(error) => {
+                eprintln!("{}", error);
+                1
+            }
+        });
+    }
 
     let output_options = OutputOptions {
         filename: output.to_string(),
         format: output_format.to_string(),
     };
 
-    let pixel_type = red_band.band_type();
-
     let result = match pixel_type {
-        GDALDataType::GDT_Byte => ply_bands::<u8>(
+        GDALDataType::GDT_Byte => process::<u8>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_UInt16 => ply_bands::<u16>(
+        GDALDataType::GDT_UInt16 => process::<u16>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_UInt32 => ply_bands::<u32>(
+        GDALDataType::GDT_UInt32 => process::<u32>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_Int16 => ply_bands::<i16>(
+        GDALDataType::GDT_Int16 => process::<i16>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_Int32 => ply_bands::<i32>(
+        GDALDataType::GDT_Int32 => process::<i32>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_Float32 => ply_bands::<f32>(
+        GDALDataType::GDT_Float32 => process::<f32>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
-        GDALDataType::GDT_Float64 => ply_bands::<f64>(
+        GDALDataType::GDT_Float64 => process::<f64>(
             &output_options,
             sw,
             proj,
             &red_band,
             &green_band,
             &blue_band,
+            block_lines,
+            needs_warp,
+            &resampling,
+            &stretch,
         ),
         _ => Err(Error::from_string(
             format!("Unhandled band type {}", pixel_type).to_string(),
@@ -0,0 +1,147 @@
+use gdal::raster::types::GdalType;
+use gdal::raster::RasterBand;
+
+use gdal_typed_rasterband::typed_rasterband::{GdalFrom, TypedRasterBand};
+
+use crate::error::Error;
+use crate::swath::{self, Swath};
+use crate::warp;
+
+const HISTOGRAM_BINS: usize = 2048;
+
+pub enum Stretch {
+    None,
+    MinMax,
+    Percentile(f64, f64),
+}
+
+impl Stretch {
+    pub fn parse(s: &str) -> Result<Stretch, Error> {
+        match s {
+            "none" => Ok(Stretch::None),
+            "minmax" => Ok(Stretch::MinMax),
+            other => {
+                let percentiles = other.strip_prefix('p').and_then(|rest| {
+                    let mut parts = rest.splitn(2, '-');
+                    let lo = parts.next()?.parse::<f64>().ok()?;
+                    let hi = parts.next()?.parse::<f64>().ok()?;
+                    Some((lo, hi))
+                });
+
+                percentiles
+                    .map(|(lo, hi)| Stretch::Percentile(lo, hi))
+                    .ok_or_else(|| Error::from_string(format!("Unknown stretch mode '{}'", other)))
+            }
+        }
+    }
+}
+
+// The `[lo, hi]` source-value window that maps linearly onto `[0, 255]`.
+pub struct Range {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Range {
+    pub fn to_u8(&self, value: f64) -> u8 {
+        if self.hi <= self.lo {
+            return 0;
+        }
+        let t = ((value - self.lo) / (self.hi - self.lo)).max(0.0).min(1.0);
+        (t * 255.0).round() as u8
+    }
+}
+
+// Scan `band`'s valid (non-NoData) samples to determine the `[lo, hi]`
+// stretch window: the data extremes for `minmax`, or a histogram-derived
+// percentile window for `Percentile`. Reads through the same `extract_auto`
+// block-strip path used to write pixels, so a `band` whose resolution
+// doesn't match `sw`'s is resampled the same way here as there, and this
+// costs one extra read of the source (two for `Percentile`) but not an extra
+// O(whole swath) buffer.
+pub fn compute_range<T>(
+    sw: &Swath,
+    band: &RasterBand,
+    typed: &TypedRasterBand<T>,
+    block_lines: usize,
+    nodata: Option<f64>,
+    resampling: &warp::Resampling,
+    stretch: &Stretch,
+) -> Result<Range, Error>
+where
+    T: Copy + GdalType + GdalFrom<f64> + PartialEq + Into<f64>,
+{
+    let nodata_t = nodata.map(T::gdal_from);
+    let is_valid = |v: T| match (nodata, nodata_t) {
+        (Some(nd), Some(nd_t)) => !crate::is_nodata(v, nd_t, nd),
+        _ => true,
+    };
+
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+
+    swath::extract_auto(sw, band, typed, block_lines, resampling, |_, buf| {
+        for &v in buf.data.iter() {
+            if is_valid(v) {
+                let f: f64 = v.into();
+                min = min.min(f);
+                max = max.max(f);
+            }
+        }
+        Ok(())
+    })?;
+
+    if !min.is_finite() || !max.is_finite() {
+        return Err(Error::from_string(
+            "no valid samples to compute a stretch from".to_string(),
+        ));
+    }
+
+    let (lo_pct, hi_pct) = match stretch {
+        Stretch::None | Stretch::MinMax => return Ok(Range { lo: min, hi: max }),
+        Stretch::Percentile(lo, hi) => (*lo, *hi),
+    };
+
+    if max <= min {
+        return Ok(Range { lo: min, hi: max });
+    }
+
+    let mut histogram = vec![0u64; HISTOGRAM_BINS];
+    let mut total: u64 = 0;
+    let bin_width = (max - min) / HISTOGRAM_BINS as f64;
+
+    swath::extract_auto(sw, band, typed, block_lines, resampling, |_, buf| {
+        for &v in buf.data.iter() {
+            if is_valid(v) {
+                let f: f64 = v.into();
+                let bin = (((f - min) / bin_width) as usize).min(HISTOGRAM_BINS - 1);
+                histogram[bin] += 1;
+                total += 1;
+            }
+        }
+        Ok(())
+    })?;
+
+    let lo_target = (total as f64 * lo_pct / 100.0).round() as u64;
+    let hi_target = (total as f64 * hi_pct / 100.0).round() as u64;
+
+    let mut cumulative = 0u64;
+    let mut lo_value = min;
+    let mut hi_value = max;
+    for (i, &count) in histogram.iter().enumerate() {
+        let bin_start_reached = cumulative < lo_target;
+        cumulative += count;
+        if bin_start_reached && cumulative >= lo_target {
+            lo_value = min + i as f64 * bin_width;
+        }
+        if cumulative >= hi_target {
+            hi_value = min + (i + 1) as f64 * bin_width;
+            break;
+        }
+    }
+
+    Ok(Range {
+        lo: lo_value,
+        hi: hi_value,
+    })
+}
@@ -1,6 +1,7 @@
 use gdal::raster::dataset::{Buffer, GeoTransform};
 use gdal::raster::types::GdalType;
 use gdal::raster::RasterBand;
+use gdal::spatial_ref::{CoordTransform, SpatialRef};
 
 use gdal_typed_rasterband::typed_rasterband::{GdalFrom, TypedRasterBand};
 
@@ -69,10 +70,20 @@ impl Swath {
     }
 }
 
-pub fn extract<T: Copy + GdalType + GdalFrom<f64>>(
+// Read a swath out of `band` in horizontal strips of at most `block_lines`
+// rows, invoking `each_window` with the strip's y-offset (relative to the
+// top of the swath) and its buffer. This keeps peak memory to O(one strip)
+// rather than O(whole swath), which matters for full scene-sized inputs.
+pub fn extract<T, F>(
     swath: &Swath,
     band: &TypedRasterBand<T>,
-) -> Result<Buffer<T>, Error> {
+    block_lines: usize,
+    mut each_window: F,
+) -> Result<(), Error>
+where
+    T: Copy + GdalType + GdalFrom<f64>,
+    F: FnMut(isize, Buffer<T>) -> Result<(), Error>,
+{
     let top_left_coord = (RealF64 { v: swath.gt[0] }, RealF64 { v: swath.gt[3] });
 
     let (ix, iy) = band
@@ -80,12 +91,186 @@ pub fn extract<T: Copy + GdalType + GdalFrom<f64>>(
         .geo_transform()?
         .invert(&top_left_coord);
     let top_left_idx = (ix as isize, iy as isize);
-    let size = (swath.nx as usize, swath.ny as usize);
 
-    // we require inputs to have the same resolution, so the buffer size will be the same as the
-    // window read
-    let buf = band.read(top_left_idx, size, size)?;
-    Ok(buf)
+    // we require inputs to have the same resolution, so the read window size
+    // matches the buffer size
+    let nx = swath.nx as usize;
+    let ny = swath.ny as usize;
+    let block_lines = block_lines.max(1);
+
+    let mut y_offset: usize = 0;
+    while y_offset < ny {
+        let h = block_lines.min(ny - y_offset);
+        let origin = (top_left_idx.0, top_left_idx.1 + y_offset as isize);
+        let buf = band.read(origin, (nx, h), (nx, h))?;
+        each_window(y_offset as isize, buf)?;
+        y_offset += h;
+    }
+
+    Ok(())
+}
+
+// The native block (tile/strip) height GDAL would read most efficiently from
+// `band`, used as the default `--block-lines` strip size when the caller
+// doesn't ask for a specific one.
+pub fn native_block_height(band: &RasterBand) -> usize {
+    band.block_size().1
+}
+
+// True when `band`'s own pixel spacing already matches `swath`'s, i.e. the
+// fast, non-resampling `extract` path applies.
+fn same_resolution(swath: &Swath, band: &RasterBand) -> Result<bool, Error> {
+    let src_gt = band.owning_dataset().geo_transform()?;
+    Ok((src_gt[1] - swath.gt[1]).abs() < 1e-9 && (src_gt[5] - swath.gt[5]).abs() < 1e-9)
+}
+
+// Read a swath out of `band` in horizontal strips, resampling between
+// `band`'s own pixel grid and `swath`'s common one when they differ
+// (`crate::warp::Resampling` selects how), and taking the `extract` fast
+// path when they already match.
+pub fn extract_auto<T, F>(
+    swath: &Swath,
+    band: &RasterBand,
+    typed: &TypedRasterBand<T>,
+    block_lines: usize,
+    resampling: &crate::warp::Resampling,
+    each_window: F,
+) -> Result<(), Error>
+where
+    T: Copy + GdalType + GdalFrom<f64>,
+    F: FnMut(isize, Buffer<T>) -> Result<(), Error>,
+{
+    if same_resolution(swath, band)? {
+        extract(swath, typed, block_lines, each_window)
+    } else {
+        extract_resampled(swath, band, block_lines, resampling, each_window)
+    }
+}
+
+// Like `extract`, but for a source band whose pixel spacing differs from
+// `swath`'s. Each output strip's window is translated into the equivalent
+// window on `band`'s own grid, and the window/buffer sizes are passed to
+// GDAL's RasterIO separately so it resamples between the two.
+fn extract_resampled<T, F>(
+    swath: &Swath,
+    band: &RasterBand,
+    block_lines: usize,
+    resampling: &crate::warp::Resampling,
+    mut each_window: F,
+) -> Result<(), Error>
+where
+    T: Copy + GdalType + GdalFrom<f64>,
+    F: FnMut(isize, Buffer<T>) -> Result<(), Error>,
+{
+    let src_gt = band.owning_dataset().geo_transform()?;
+
+    let top_left_coord = (RealF64 { v: swath.gt[0] }, RealF64 { v: swath.gt[3] });
+    let (fx, fy) = src_gt.invert(&top_left_coord);
+
+    let x_ratio = swath.gt[1] / src_gt[1];
+    let y_ratio = swath.gt[5] / src_gt[5];
+
+    let nx = swath.nx as usize;
+    let ny = swath.ny as usize;
+    let block_lines = block_lines.max(1);
+
+    let mut y_offset: usize = 0;
+    while y_offset < ny {
+        let h = block_lines.min(ny - y_offset);
+
+        let src_origin = (fx as isize, (fy + y_offset as f64 * y_ratio) as isize);
+        let src_window = (
+            ((nx as f64) * x_ratio).round().max(1.0) as usize,
+            ((h as f64) * y_ratio).round().max(1.0) as usize,
+        );
+
+        let buf = crate::warp::read_resampled::<T>(band, src_origin, src_window, (nx, h), resampling)?;
+        each_window(y_offset as isize, buf)?;
+        y_offset += h;
+    }
+
+    Ok(())
+}
+
+pub enum ResolutionChoice {
+    Source,
+    Finest,
+    Coarsest,
+    Value(f64),
+}
+
+impl ResolutionChoice {
+    pub fn parse(s: &str) -> Result<ResolutionChoice, Error> {
+        match s {
+            "source" => Ok(ResolutionChoice::Source),
+            "finest" => Ok(ResolutionChoice::Finest),
+            "coarsest" => Ok(ResolutionChoice::Coarsest),
+            other => other
+                .parse::<f64>()
+                .map(ResolutionChoice::Value)
+                .map_err(|_| Error::from_string(format!("Invalid --resolution '{}'", other))),
+        }
+    }
+}
+
+// Like `intersection`, but the output pixel size is chosen independently of
+// any single source's via `resolution`, so bands at different resolutions
+// (e.g. a 10 m and a 20 m channel) can still be combined onto one common
+// grid; `extract_auto` resamples each source into it during compositing.
+pub fn intersection_at_resolution(
+    bands: &[&RasterBand],
+    resolution: &ResolutionChoice,
+) -> Result<Swath, Error> {
+    if bands.len() == 0 {
+        return Err(Error::from_string("No bands provided".to_string()));
+    }
+
+    let swaths: Vec<Swath> = bands.iter().map(|b| Swath::from_band(b)).collect();
+
+    let left: Vec<RealF64> = swaths.iter().map(|b| b.left_extreme()).collect();
+    let right: Vec<RealF64> = swaths.iter().map(|b| b.right_extreme()).collect();
+    let bottom: Vec<RealF64> = swaths.iter().map(|b| b.bottom_extreme()).collect();
+    let top: Vec<RealF64> = swaths.iter().map(|b| b.top_extreme()).collect();
+
+    let rightmost_left = left.iter().max().unwrap();
+    let leftmost_right = right.iter().min().unwrap();
+    let upper_bottom = bottom.iter().max().unwrap();
+    let lower_top = top.iter().min().unwrap();
+
+    if (rightmost_left > leftmost_right) || (upper_bottom > lower_top) {
+        return Err(Error::from_string(
+            "No valid intersection between bands".to_string(),
+        ));
+    }
+
+    let spacings: Vec<f64> = swaths.iter().map(|sw| sw.gt[1].abs()).collect();
+    let pixel_size = match resolution {
+        ResolutionChoice::Source => spacings[0],
+        ResolutionChoice::Finest => spacings.iter().cloned().fold(f64::INFINITY, f64::min),
+        ResolutionChoice::Coarsest => spacings.iter().cloned().fold(0.0, f64::max),
+        ResolutionChoice::Value(v) => *v,
+    };
+
+    let gt_fst = bands[0].owning_dataset().geo_transform().unwrap();
+    let proj_fst = bands[0].owning_dataset().projection();
+
+    let gt: [f64; 6] = [
+        rightmost_left.v,
+        pixel_size,
+        gt_fst[2],
+        lower_top.v,
+        gt_fst[4],
+        -pixel_size,
+    ];
+
+    let (nx, ny) = gt.imsize(&(*leftmost_right, *upper_bottom));
+
+    Ok(Swath {
+        nx,
+        ny,
+        gt,
+        proj: proj_fst,
+    })
 }
 
 // Return the rectangular swath representing the intersection of a sequence of
@@ -134,3 +319,96 @@ pub fn intersection(bands: &[&RasterBand]) -> Result<Swath, Error> {
         })
     }
 }
+
+// The pixel size `band`'s own geotransform implies once reprojected into
+// `target_srs`: the distance a one-pixel step in each source axis covers
+// after transforming it, not `band`'s untransformed spacing (which is in the
+// wrong units/CRS entirely once the source and target CRSes differ, e.g.
+// degrees vs. metres).
+fn pixel_size_in(band: &RasterBand, target_srs: &SpatialRef) -> Result<(f64, f64), Error> {
+    let sw = Swath::from_band(band);
+    let src_srs = SpatialRef::from_wkt(&sw.proj)?;
+    let transform = CoordTransform::new(&src_srs, target_srs)?;
+
+    let mut xs = vec![sw.gt[0], sw.gt[0] + sw.gt[1], sw.gt[0]];
+    let mut ys = vec![sw.gt[3], sw.gt[3], sw.gt[3] + sw.gt[5]];
+    let mut zs = vec![0.0; 3];
+    transform.transform_coords(&mut xs, &mut ys, &mut zs);
+
+    let pixel_width = ((xs[1] - xs[0]).powi(2) + (ys[1] - ys[0]).powi(2)).sqrt();
+    let pixel_height = ((xs[2] - xs[0]).powi(2) + (ys[2] - ys[0]).powi(2)).sqrt();
+
+    Ok((pixel_width, pixel_height))
+}
+
+// Like `intersection`, but computed in `target_srs` rather than requiring the
+// bands to already share a projection. Each band's corners are transformed
+// into `target_srs` before the same left/right/bottom/top reduction is
+// applied, so mismatched-CRS inputs can still be combined. Pixel spacing is
+// derived from the first band's own spacing as it maps into `target_srs`
+// (see `pixel_size_in`), since the source CRS's spacing is meaningless once
+// reprojected into a CRS with different units.
+pub fn intersection_in(bands: &[&RasterBand], target_srs: &SpatialRef) -> Result<Swath, Error> {
+    if bands.len() == 0 {
+        return Err(Error::from_string("No bands provided".to_string()));
+    }
+
+    let mut left = Vec::with_capacity(bands.len());
+    let mut right = Vec::with_capacity(bands.len());
+    let mut bottom = Vec::with_capacity(bands.len());
+    let mut top = Vec::with_capacity(bands.len());
+
+    for band in bands {
+        let sw = Swath::from_band(band);
+        let src_srs = SpatialRef::from_wkt(&sw.proj)?;
+        let transform = CoordTransform::new(&src_srs, target_srs)?;
+
+        let corners = sw.corners();
+        let mut xs: Vec<f64> = corners.iter().map(|pt| pt.0.v).collect();
+        let mut ys: Vec<f64> = corners.iter().map(|pt| pt.1.v).collect();
+        let mut zs: Vec<f64> = vec![0.0; corners.len()];
+        transform.transform_coords(&mut xs, &mut ys, &mut zs);
+
+        let transformed: Vec<(RealF64, RealF64)> = xs
+            .into_iter()
+            .zip(ys.into_iter())
+            .map(|(x, y)| (RealF64 { v: x }, RealF64 { v: y }))
+            .collect();
+
+        left.push(transformed.iter().min_by_key(|pt| pt.0).unwrap().0);
+        right.push(transformed.iter().max_by_key(|pt| pt.0).unwrap().0);
+        bottom.push(transformed.iter().min_by_key(|pt| pt.1).unwrap().1);
+        top.push(transformed.iter().max_by_key(|pt| pt.1).unwrap().1);
+    }
+
+    let rightmost_left = left.iter().max().unwrap();
+    let leftmost_right = right.iter().min().unwrap();
+    let upper_bottom = bottom.iter().max().unwrap();
+    let lower_top = top.iter().min().unwrap();
+
+    if (rightmost_left > leftmost_right) || (upper_bottom > lower_top) {
+        return Err(Error::from_string(
+            "No valid intersection between bands in target SRS".to_string(),
+        ));
+    }
+
+    let (pixel_width, pixel_height) = pixel_size_in(bands[0], target_srs)?;
+
+    let gt: [f64; 6] = [
+        rightmost_left.v,
+        pixel_width,
+        0.0,
+        lower_top.v,
+        0.0,
+        -pixel_height,
+    ];
+
+    let (nx, ny) = gt.imsize(&(*leftmost_right, *upper_bottom));
+
+    Ok(Swath {
+        nx,
+        ny,
+        gt,
+        proj: target_srs.to_wkt()?,
+    })
+}
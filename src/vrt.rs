@@ -0,0 +1,128 @@
+use std::fs;
+
+use gdal::raster::RasterBand;
+
+use gdal_sys::GDALDataType;
+
+use crate::error::Error;
+use crate::swath::Swath;
+use crate::transform::Transform2;
+use crate::types::RealF64;
+
+// One false-colour channel's VRT source: which file/band the composite reads
+// from, and the pixel offset (within that source) of the swath's top-left
+// corner, derived the same way `swath::extract` locates its read window.
+struct Channel {
+    path: String,
+    band_index: isize,
+    src_offset: (isize, isize),
+}
+
+// Canonicalize `path` before embedding it in the VRT: `SimpleSource` is
+// written with `relativeToVRT="0"`, which GDAL resolves against whatever the
+// current directory happens to be when the VRT is later opened, not the
+// directory `plyband` was run from. An absolute, canonical path makes that
+// resolution independent of where (or from where) the VRT is subsequently
+// read.
+fn channel(sw: &Swath, path: &str, band_index: isize, band: &RasterBand) -> Result<Channel, Error> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| Error::from_string(format!("failed to resolve '{}': {}", path, e)))?;
+
+    let top_left_coord = (RealF64 { v: sw.gt[0] }, RealF64 { v: sw.gt[3] });
+    let (ix, iy) = band
+        .owning_dataset()
+        .geo_transform()?
+        .invert(&top_left_coord);
+
+    Ok(Channel {
+        path: canonical.to_string_lossy().into_owned(),
+        band_index,
+        src_offset: (ix as isize, iy as isize),
+    })
+}
+
+fn gdal_type_name(t: GDALDataType::Type) -> Result<&'static str, Error> {
+    match t {
+        GDALDataType::GDT_Byte => Ok("Byte"),
+        GDALDataType::GDT_UInt16 => Ok("UInt16"),
+        GDALDataType::GDT_UInt32 => Ok("UInt32"),
+        GDALDataType::GDT_Int16 => Ok("Int16"),
+        GDALDataType::GDT_Int32 => Ok("Int32"),
+        GDALDataType::GDT_Float32 => Ok("Float32"),
+        GDALDataType::GDT_Float64 => Ok("Float64"),
+        other => Err(Error::from_string(format!(
+            "Unhandled band type {}",
+            other
+        ))),
+    }
+}
+
+fn color_interp(index: usize) -> &'static str {
+    match index {
+        0 => "Red",
+        1 => "Green",
+        _ => "Blue",
+    }
+}
+
+fn band_xml(index: usize, data_type: &str, nx: isize, ny: isize, channel: &Channel) -> String {
+    format!(
+        r#"  <VRTRasterBand dataType="{data_type}" band="{band}">
+    <ColorInterp>{color_interp}</ColorInterp>
+    <SimpleSource>
+      <SourceFilename relativeToVRT="0">{path}</SourceFilename>
+      <SourceBand>{src_band}</SourceBand>
+      <SrcRect xOff="{src_x}" yOff="{src_y}" xSize="{nx}" ySize="{ny}"/>
+      <DstRect xOff="0" yOff="0" xSize="{nx}" ySize="{ny}"/>
+    </SimpleSource>
+  </VRTRasterBand>
+"#,
+        data_type = data_type,
+        band = index + 1,
+        color_interp = color_interp(index),
+        path = channel.path,
+        src_band = channel.band_index,
+        src_x = channel.src_offset.0,
+        src_y = channel.src_offset.1,
+        nx = nx,
+        ny = ny,
+    )
+}
+
+// Emit a VRT describing `sw` as three bands, each a SimpleSource pointing at
+// the original red/green/blue file and band, instead of materializing pixels
+// through `write_raster`. GDAL resolves the SimpleSources lazily, so this is
+// a near-instant, zero-copy way to define the composite.
+pub fn write(
+    filename: &str,
+    sw: &Swath,
+    pixel_type: GDALDataType::Type,
+    channels: [(&str, isize, &RasterBand); 3],
+) -> Result<(), Error> {
+    let data_type = gdal_type_name(pixel_type)?;
+    let nx = sw.nx.abs();
+    let ny = sw.ny.abs();
+
+    let mut body = String::new();
+    for (index, (path, band_index, band)) in channels.iter().enumerate() {
+        let ch = self::channel(sw, path, *band_index, band)?;
+        body.push_str(&band_xml(index, data_type, nx, ny, &ch));
+    }
+
+    let xml = format!(
+        "<VRTDataset rasterXSize=\"{nx}\" rasterYSize=\"{ny}\">\n  <SRS>{srs}</SRS>\n  <GeoTransform>{gt}</GeoTransform>\n{body}</VRTDataset>\n",
+        nx = nx,
+        ny = ny,
+        srs = sw.proj,
+        gt = sw
+            .gt
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>()
+            .join(", "),
+        body = body,
+    );
+
+    fs::write(filename, xml)
+        .map_err(|e| Error::from_string(format!("failed to write VRT '{}': {}", filename, e)))
+}
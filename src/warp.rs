@@ -0,0 +1,196 @@
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use gdal::raster::dataset::Buffer;
+use gdal::raster::types::GdalType;
+use gdal::raster::{Dataset, Driver, RasterBand};
+use gdal::spatial_ref::SpatialRef;
+
+use gdal_sys::{
+    CPLErr, CPLMalloc, GDALCreateWarpOptions, GDALDestroyWarpOptions, GDALGetBandNumber,
+    GDALRWFlag, GDALRIOResampleAlg, GDALRasterIOEx, GDALRasterIOExtraArg, GDALReprojectImage,
+    GDALResampleAlg,
+};
+
+use gdal_typed_rasterband::typed_rasterband::GdalFrom;
+
+use crate::error::Error;
+use crate::swath::Swath;
+
+pub enum Resampling {
+    Nearest,
+    Bilinear,
+    Cubic,
+}
+
+impl Resampling {
+    pub fn parse(s: &str) -> Result<Resampling, Error> {
+        match s {
+            "nearest" => Ok(Resampling::Nearest),
+            "bilinear" => Ok(Resampling::Bilinear),
+            "cubic" => Ok(Resampling::Cubic),
+            other => Err(Error::from_string(format!(
+                "Unknown resampling method '{}'",
+                other
+            ))),
+        }
+    }
+
+    fn to_gdal(&self) -> GDALResampleAlg::Type {
+        match self {
+            Resampling::Nearest => GDALResampleAlg::GRA_NearestNeighbour,
+            Resampling::Bilinear => GDALResampleAlg::GRA_Bilinear,
+            Resampling::Cubic => GDALResampleAlg::GRA_Cubic,
+        }
+    }
+
+    fn to_gdal_rio(&self) -> GDALRIOResampleAlg::Type {
+        match self {
+            Resampling::Nearest => GDALRIOResampleAlg::GRIORA_NearestNeighbour,
+            Resampling::Bilinear => GDALRIOResampleAlg::GRIORA_Bilinear,
+            Resampling::Cubic => GDALRIOResampleAlg::GRIORA_Cubic,
+        }
+    }
+}
+
+// Parse a `--target-srs` value, accepting either a raw WKT string or an
+// `EPSG:<code>` shorthand.
+pub fn parse_target_srs(input: &str) -> Result<SpatialRef, Error> {
+    if let Some(code) = input.strip_prefix("EPSG:") {
+        let code: u32 = code
+            .parse()
+            .map_err(|_| Error::from_string(format!("Invalid EPSG code '{}'", input)))?;
+        SpatialRef::from_epsg(code).map_err(Error::from)
+    } else {
+        SpatialRef::from_wkt(input).map_err(Error::from)
+    }
+}
+
+// Resample `band` into a new in-memory, single-band dataset covering
+// `target`, in `target`'s projection and grid. Used to bring a mismatched-CRS
+// input onto the common grid `ply_bands` expects before compositing. The
+// source's NoData value, if any, carries over onto the warped band, both so
+// `GDALReprojectImage` itself excludes NoData source pixels from the
+// resampling and so the caller's later `band.no_data_value()` lookup (used
+// for alpha-masking) still sees it once the band has been substituted for
+// its warped copy.
+pub fn warp_to_swath<T: Copy + GdalType>(
+    band: &RasterBand,
+    target: &Swath,
+    resampling: &Resampling,
+) -> Result<Dataset, Error> {
+    let mem_driver = Driver::get("MEM").unwrap();
+    let warped = mem_driver
+        .create_with_band_type::<T>("", target.nx.abs(), target.ny.abs(), 1)
+        .map_err(Error::from)?;
+
+    warped.set_projection(&target.proj)?;
+    warped.set_geo_transform(&target.gt)?;
+
+    let nodata = band.no_data_value();
+    if let Some(nd) = nodata {
+        warped.rasterband(1)?.set_no_data_value(nd)?;
+    }
+
+    let src_ds = band.owning_dataset();
+    let src_wkt = CString::new(src_ds.projection()).unwrap();
+    let dst_wkt = CString::new(target.proj.clone()).unwrap();
+
+    let warp_options = unsafe {
+        let options = GDALCreateWarpOptions();
+        (*options).hSrcDS = src_ds.c_dataset();
+        (*options).hDstDS = warped.c_dataset();
+        (*options).nBandCount = 1;
+        (*options).panSrcBands = CPLMalloc(mem::size_of::<c_int>()) as *mut c_int;
+        *(*options).panSrcBands = GDALGetBandNumber(band.c_rasterband());
+        (*options).panDstBands = CPLMalloc(mem::size_of::<c_int>()) as *mut c_int;
+        *(*options).panDstBands = 1;
+
+        if let Some(nd) = nodata {
+            (*options).padfSrcNoDataReal = CPLMalloc(mem::size_of::<f64>()) as *mut f64;
+            *(*options).padfSrcNoDataReal = nd;
+            (*options).padfDstNoDataReal = CPLMalloc(mem::size_of::<f64>()) as *mut f64;
+            *(*options).padfDstNoDataReal = nd;
+        }
+
+        options
+    };
+
+    let result = unsafe {
+        GDALReprojectImage(
+            src_ds.c_dataset(),
+            src_wkt.as_ptr(),
+            warped.c_dataset(),
+            dst_wkt.as_ptr(),
+            resampling.to_gdal(),
+            0.0,
+            0.0,
+            None,
+            ptr::null_mut(),
+            warp_options,
+        )
+    };
+
+    unsafe { GDALDestroyWarpOptions(warp_options) };
+
+    if result != CPLErr::CE_None {
+        return Err(Error::from_string("GDALReprojectImage failed".to_string()));
+    }
+
+    Ok(warped)
+}
+
+// Read `window_size` source pixels starting at `window_origin` out of `band`
+// into a `buffer_size` buffer, letting GDAL's RasterIO resample between the
+// two using `resampling`. Used by `swath::extract_auto` when a source band's
+// resolution doesn't match the common grid's.
+pub fn read_resampled<T: Copy + GdalType + GdalFrom<f64>>(
+    band: &RasterBand,
+    window_origin: (isize, isize),
+    window_size: (usize, usize),
+    buffer_size: (usize, usize),
+    resampling: &Resampling,
+) -> Result<Buffer<T>, Error> {
+    let mut data: Vec<T> = vec![T::gdal_from(0.0); buffer_size.0 * buffer_size.1];
+
+    let mut extra_arg = GDALRasterIOExtraArg {
+        nVersion: 1,
+        eResampleAlg: resampling.to_gdal_rio(),
+        pfnProgress: None,
+        pProgressData: ptr::null_mut(),
+        bFloatingPointWindowValidity: 0,
+        dfXOff: 0.0,
+        dfYOff: 0.0,
+        dfXSize: 0.0,
+        dfYSize: 0.0,
+    };
+
+    let result = unsafe {
+        GDALRasterIOEx(
+            band.c_rasterband(),
+            GDALRWFlag::GF_Read,
+            window_origin.0 as i32,
+            window_origin.1 as i32,
+            window_size.0 as i32,
+            window_size.1 as i32,
+            data.as_mut_ptr() as *mut c_void,
+            buffer_size.0 as i32,
+            buffer_size.1 as i32,
+            T::gdal_type(),
+            0,
+            0,
+            &mut extra_arg,
+        )
+    };
+
+    if result != CPLErr::CE_None {
+        return Err(Error::from_string("GDALRasterIOEx failed".to_string()));
+    }
+
+    Ok(Buffer {
+        size: buffer_size,
+        data,
+    })
+}